@@ -1,3 +1,6 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::collections::HashMap;
 use std::collections::VecDeque;
 use std::fs;
 use std::env;
@@ -5,34 +8,248 @@ use std::process;
 
 type Point = (usize, usize);
 
+trait MapSource {
+    fn read(&self, name: &str) -> Result<String, String>;
+}
+
+struct RealFs;
+
+impl MapSource for RealFs {
+    fn read(&self, name: &str) -> Result<String, String> {
+        fs::read_to_string(name).map_err(|e| format!("Failed to read file '{}': {}", name, e))
+    }
+}
+
+#[cfg(test)]
+struct InMemoryFs {
+    files: HashMap<String, String>,
+}
+
+#[cfg(test)]
+impl InMemoryFs {
+    fn new() -> Self {
+        InMemoryFs {
+            files: HashMap::new(),
+        }
+    }
+
+    fn with_file(mut self, name: &str, content: &str) -> Self {
+        self.files.insert(name.to_string(), content.to_string());
+        self
+    }
+}
+
+#[cfg(test)]
+impl MapSource for InMemoryFs {
+    fn read(&self, name: &str) -> Result<String, String> {
+        self.files
+            .get(name)
+            .cloned()
+            .ok_or_else(|| format!("Failed to read file '{}': no such file", name))
+    }
+}
+
+fn terrain_cost(ch: char) -> Option<u32> {
+    match ch {
+        '#' => None,
+        _ => Some(1),
+    }
+}
+
+enum LegendEntry {
+    Terrain(char, Option<u32>),
+    Portal(char, char),
+}
+
+fn skip_spaces(input: &str) -> &str {
+    input.trim_start_matches(' ')
+}
+
+fn parse_char_token(input: &str) -> Result<(char, &str), String> {
+    let input = skip_spaces(input);
+    let mut chars = input.chars();
+    match chars.next() {
+        Some(c) => Ok((c, chars.as_str())),
+        None => Err("expected a character".to_string()),
+    }
+}
+
+fn parse_literal<'a>(input: &'a str, literal: &str) -> Result<&'a str, String> {
+    let input = skip_spaces(input);
+    input
+        .strip_prefix(literal)
+        .ok_or_else(|| format!("expected '{}'", literal))
+}
+
+fn parse_word(input: &str) -> Result<(&str, &str), String> {
+    let input = skip_spaces(input);
+    let len = input.chars().take_while(|c| c.is_alphabetic()).count();
+    if len == 0 {
+        return Err("expected a word".to_string());
+    }
+    Ok((&input[..len], &input[len..]))
+}
+
+fn parse_u32(input: &str) -> Result<(u32, &str), String> {
+    let input = skip_spaces(input);
+    let len = input.chars().take_while(|c| c.is_ascii_digit()).count();
+    if len == 0 {
+        return Err("expected a number".to_string());
+    }
+    let (digits, rest) = input.split_at(len);
+    digits
+        .parse::<u32>()
+        .map(|n| (n, rest))
+        .map_err(|e| e.to_string())
+}
+
+fn parse_legend_entry(line: &str) -> Result<LegendEntry, String> {
+    let (first, rest) = parse_char_token(line)?;
+    if let Ok(rest) = parse_literal(rest, "=") {
+        let (_name, rest) = parse_word(rest)?;
+        let rest = skip_spaces(rest);
+        if rest.is_empty() {
+            Ok(LegendEntry::Terrain(first, None))
+        } else {
+            let (cost, rest) = parse_u32(rest)?;
+            if !skip_spaces(rest).is_empty() {
+                return Err("unexpected trailing input in terrain entry".to_string());
+            }
+            Ok(LegendEntry::Terrain(first, Some(cost)))
+        }
+    } else {
+        let (second, rest) = parse_char_token(rest)?;
+        if !skip_spaces(rest).is_empty() {
+            return Err("unexpected trailing input in portal entry".to_string());
+        }
+        if !first.is_ascii_digit() || !second.is_ascii_digit() {
+            return Err("portal links must name two digits".to_string());
+        }
+        Ok(LegendEntry::Portal(first, second))
+    }
+}
+
+fn split_header(content: &str) -> (Option<&str>, &str) {
+    let mut pos = 0;
+    for line in content.split_inclusive('\n') {
+        if line.trim_end_matches(['\r', '\n']).trim() == "---" {
+            let header_end = pos;
+            let body_start = pos + line.len();
+            return (
+                Some(&content[..header_end]),
+                content.get(body_start..).unwrap_or(""),
+            );
+        }
+        pos += line.len();
+    }
+    (None, content)
+}
+
+fn find_in_grid(grid: &[Vec<char>], target: char) -> Option<Point> {
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &ch) in row.iter().enumerate() {
+            if ch == target {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
 #[derive(Debug)]
 struct Map {
     grid: Vec<Vec<char>>,
     width: usize,
     height: usize,
+    cost: HashMap<char, Option<u32>>,
+    portals: HashMap<Point, Point>,
 }
 
 impl Map {
     fn from_file(filename: &str) -> Result<Self, String> {
-        let content = fs::read_to_string(filename)
-            .map_err(|e| format!("Failed to read file '{}': {}", filename, e))?;
-        
-        let lines: Vec<&str> = content.lines().collect();
+        Self::from_source(&RealFs, filename)
+    }
+
+    fn from_source(source: &dyn MapSource, name: &str) -> Result<Self, String> {
+        let content = source.read(name)?;
+        let (header, body) = split_header(&content);
+
+        let mut cost = HashMap::new();
+        let mut portal_links = Vec::new();
+        if let Some(header) = header {
+            for line in header.lines() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_legend_entry(line)? {
+                    LegendEntry::Terrain(ch, c) => {
+                        cost.insert(ch, c);
+                    }
+                    LegendEntry::Portal(a, b) => portal_links.push((a, b)),
+                }
+            }
+        }
+
+        let lines: Vec<&str> = body.lines().collect();
         if lines.is_empty() {
             return Err("File is empty".to_string());
         }
-        
+
         let height = lines.len();
         let width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
         let mut grid = vec![vec![' '; width]; height];
-        
+
         for (y, line) in lines.iter().enumerate() {
             for (x, ch) in line.chars().enumerate() {
                 grid[y][x] = ch;
             }
         }
-        
-        Ok(Map { grid, width, height })
+
+        let mut portals = HashMap::new();
+        for (a, b) in portal_links {
+            let pa = find_in_grid(&grid, a)
+                .ok_or_else(|| format!("portal cell '{}' not found on the grid", a))?;
+            let pb = find_in_grid(&grid, b)
+                .ok_or_else(|| format!("portal cell '{}' not found on the grid", b))?;
+            portals.insert(pa, pb);
+            portals.insert(pb, pa);
+        }
+
+        Ok(Map {
+            grid,
+            width,
+            height,
+            cost,
+            portals,
+        })
+    }
+
+    fn cell_cost(&self, ch: char) -> Option<u32> {
+        match self.cost.get(&ch) {
+            Some(c) => *c,
+            None => terrain_cost(ch),
+        }
+    }
+
+    fn warp(&self, p: Point) -> Point {
+        self.portals.get(&p).copied().unwrap_or(p)
+    }
+
+    fn path_cost(&self, path: &[Point]) -> u32 {
+        path.iter()
+            .map(|&(x, y)| self.cell_cost(self.get(x, y)).unwrap_or(0))
+            .sum()
+    }
+
+    #[cfg(test)]
+    fn from_grid(grid: Vec<Vec<char>>, width: usize, height: usize) -> Self {
+        Map {
+            grid,
+            width,
+            height,
+            cost: HashMap::new(),
+            portals: HashMap::new(),
+        }
     }
 
     fn find(&self, target: char) -> Option<Point> {
@@ -46,6 +263,139 @@ impl Map {
         None
     }
 
+    fn find_all(&self, target: char) -> Vec<Point> {
+        let mut points = Vec::new();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.grid[y][x] == target {
+                    points.push((x, y));
+                }
+            }
+        }
+        points
+    }
+
+    fn route_through(
+        &self,
+        start: Point,
+        waypoints: &[Point],
+        return_to_start: bool,
+    ) -> Option<Vec<Point>> {
+        let n = waypoints.len();
+        if n == 0 {
+            return Some(Vec::new());
+        }
+
+        // nodes[0] is `start`, nodes[1..] are the waypoints.
+        let mut nodes = Vec::with_capacity(n + 1);
+        nodes.push(start);
+        nodes.extend_from_slice(waypoints);
+
+        let weighted = !self.cost.is_empty();
+        let mut dist = vec![vec![None; n + 1]; n + 1];
+        let mut segment = vec![vec![None; n + 1]; n + 1];
+        for i in 0..=n {
+            for j in 0..=n {
+                if i == j {
+                    continue;
+                }
+                let path = if weighted {
+                    self.astar(nodes[i], nodes[j])
+                } else {
+                    self.bfs(nodes[i], nodes[j])
+                };
+                if let Some(path) = path {
+                    let cost = if weighted {
+                        self.path_cost(&path) as usize
+                    } else {
+                        path.len()
+                    };
+                    dist[i][j] = Some(cost);
+                    segment[i][j] = Some(path);
+                }
+            }
+        }
+
+        // dp[mask][last] = (cost, predecessor) of the cheapest way to have
+        // visited exactly the waypoints in `mask`, ending at waypoint `last`.
+        let full_mask = (1usize << n) - 1;
+        let mut dp = vec![vec![None; n]; 1 << n];
+        for j in 0..n {
+            if let Some(d) = dist[0][j + 1] {
+                dp[1 << j][j] = Some((d, None));
+            }
+        }
+
+        for mask in 1..=full_mask {
+            for last in 0..n {
+                let Some((cost, _)) = dp[mask][last] else {
+                    continue;
+                };
+                if mask & (1 << last) == 0 {
+                    continue;
+                }
+                for j in 0..n {
+                    if mask & (1 << j) != 0 {
+                        continue;
+                    }
+                    let Some(step) = dist[last + 1][j + 1] else {
+                        continue;
+                    };
+                    let next_mask = mask | (1 << j);
+                    let next_cost = cost + step;
+                    if dp[next_mask][j].is_none_or(|(c, _)| next_cost < c) {
+                        dp[next_mask][j] = Some((next_cost, Some(last)));
+                    }
+                }
+            }
+        }
+
+        let mut best: Option<(usize, usize)> = None;
+        for last in 0..n {
+            let Some((cost, _)) = dp[full_mask][last] else {
+                continue;
+            };
+            let total = if return_to_start {
+                match dist[last + 1][0] {
+                    Some(back) => cost + back,
+                    None => continue,
+                }
+            } else {
+                cost
+            };
+            if best.is_none_or(|(c, _)| total < c) {
+                best = Some((total, last));
+            }
+        }
+        let (_, mut last) = best?;
+
+        // Walk the predecessor chain to recover waypoint visiting order.
+        let mut order = Vec::with_capacity(n);
+        let mut mask = full_mask;
+        loop {
+            order.push(last);
+            match dp[mask][last]?.1 {
+                Some(prev) => {
+                    mask &= !(1 << last);
+                    last = prev;
+                }
+                None => break,
+            }
+        }
+        order.reverse();
+
+        let mut path = Vec::new();
+        let mut prev = 0;
+        for &idx in &order {
+            path.extend(segment[prev][idx + 1].clone()?);
+            prev = idx + 1;
+        }
+        if return_to_start {
+            path.extend(segment[prev][0].clone()?);
+        }
+        Some(path)
+    }
+
     fn get(&self, x: usize, y: usize) -> char {
         self.grid[y][x]
     }
@@ -86,8 +436,12 @@ impl Map {
                 let nx = x as isize + dx;
                 let ny = y as isize + dy;
                 let (nx_norm, ny_norm) = self.normalize(nx, ny);
-                
-                if self.get(nx_norm, ny_norm) != '#' && !visited[ny_norm][nx_norm] {
+                if self.cell_cost(self.get(nx_norm, ny_norm)).is_none() {
+                    continue;
+                }
+                let (nx_norm, ny_norm) = self.warp((nx_norm, ny_norm));
+
+                if !visited[ny_norm][nx_norm] {
                     visited[ny_norm][nx_norm] = true;
                     parent[ny_norm][nx_norm] = Some((x, y));
                     queue.push_back((nx_norm, ny_norm));
@@ -97,6 +451,70 @@ impl Map {
         None
     }
 
+    fn min_cost(&self) -> u32 {
+        self.cost
+            .values()
+            .filter_map(|c| *c)
+            .chain(std::iter::once(1))
+            .min()
+            .unwrap_or(1)
+    }
+
+    fn astar(&self, start: Point, end: Point) -> Option<Vec<Point>> {
+        let min_cost = self.min_cost();
+        let mut dist = vec![vec![u32::MAX; self.width]; self.height];
+        let mut parent = vec![vec![None; self.width]; self.height];
+        let mut heap = BinaryHeap::new();
+
+        dist[start.1][start.0] = 0;
+        heap.push((Reverse(self.heuristic(start, end, min_cost)), start));
+
+        let directions = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+        while let Some((Reverse(_), (x, y))) = heap.pop() {
+            if (x, y) == end {
+                let mut path = Vec::new();
+                let mut current = end;
+                while current != start {
+                    path.push(current);
+                    current = parent[current.1][current.0].unwrap();
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let g = dist[y][x];
+
+            for &(dx, dy) in &directions {
+                let nx = x as isize + dx;
+                let ny = y as isize + dy;
+                let (nx_norm, ny_norm) = self.normalize(nx, ny);
+
+                let Some(step_cost) = self.cell_cost(self.get(nx_norm, ny_norm)) else {
+                    continue;
+                };
+                let (nx_norm, ny_norm) = self.warp((nx_norm, ny_norm));
+
+                let next_g = g + step_cost;
+                if next_g < dist[ny_norm][nx_norm] {
+                    dist[ny_norm][nx_norm] = next_g;
+                    parent[ny_norm][nx_norm] = Some((x, y));
+                    let f = next_g + self.heuristic((nx_norm, ny_norm), end, min_cost);
+                    heap.push((Reverse(f), (nx_norm, ny_norm)));
+                }
+            }
+        }
+        None
+    }
+
+    fn heuristic(&self, from: Point, to: Point, min_cost: u32) -> u32 {
+        let dx = (from.0 as isize - to.0 as isize).unsigned_abs();
+        let dy = (from.1 as isize - to.1 as isize).unsigned_abs();
+        let dx = dx.min(self.width - dx);
+        let dy = dy.min(self.height - dy);
+        (dx + dy) as u32 * min_cost
+    }
+
     fn mark_path(&mut self, path: &[Point]) {
         for &(x, y) in path {
             if self.get(x, y) != 'i' && self.get(x, y) != 'O' {
@@ -114,34 +532,205 @@ impl Map {
     }
 }
 
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains('*') || pattern.contains('?') || pattern.contains('[')
+}
+
+fn split_glob_prefix(pattern: &str) -> (String, String) {
+    let components: Vec<&str> = pattern.split('/').collect();
+    let glob_idx = components
+        .iter()
+        .position(|c| is_glob(c))
+        .unwrap_or(components.len());
+
+    let prefix = components[..glob_idx].join("/");
+    let prefix = if prefix.is_empty() { ".".to_string() } else { prefix };
+    let rest = components[glob_idx..].join("/");
+    (prefix, rest)
+}
+
+fn glob_match(pattern: &str, path: &str) -> bool {
+    let pat_parts: Vec<&str> = pattern.split('/').collect();
+    let path_parts: Vec<&str> = path.split('/').collect();
+    match_components(&pat_parts, &path_parts)
+}
+
+fn match_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => {
+            match_components(rest, path)
+                || (!path.is_empty() && match_components(pattern, &path[1..]))
+        }
+        Some((p, rest)) => match path.split_first() {
+            Some((segment, path_rest)) => {
+                match_segment(p, segment) && match_components(rest, path_rest)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_segment(pattern: &str, text: &str) -> bool {
+    let pat: Vec<char> = pattern.chars().collect();
+    let txt: Vec<char> = text.chars().collect();
+    match_segment_chars(&pat, &txt)
+}
+
+fn match_segment_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.split_first() {
+        None => text.is_empty(),
+        Some((&'*', rest)) => {
+            match_segment_chars(rest, text)
+                || (!text.is_empty() && match_segment_chars(pattern, &text[1..]))
+        }
+        Some((&'?', rest)) => !text.is_empty() && match_segment_chars(rest, &text[1..]),
+        Some((&c, rest)) => text.first() == Some(&c) && match_segment_chars(rest, &text[1..]),
+    }
+}
+
+fn walk_files(dir: &std::path::Path, max_depth: usize, depth: usize, out: &mut Vec<String>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            if depth < max_depth {
+                let rel_dir = dir.join(name);
+                walk_files(&rel_dir, max_depth, depth + 1, out);
+            }
+        } else {
+            out.push(path.to_string_lossy().to_string());
+        }
+    }
+}
+
+fn expand_glob(pattern: &str, max_depth: usize) -> Result<Vec<String>, String> {
+    let (prefix, rest) = split_glob_prefix(pattern);
+    let root = std::path::Path::new(&prefix);
+
+    let mut candidates = Vec::new();
+    walk_files(root, max_depth, 0, &mut candidates);
+
+    let mut matches: Vec<String> = candidates
+        .into_iter()
+        .filter(|path| {
+            let rel = std::path::Path::new(path)
+                .strip_prefix(root)
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_else(|_| path.clone());
+            glob_match(&rest, &rel)
+        })
+        .collect();
+    matches.sort();
+    Ok(matches)
+}
+
+fn solve_map(filename: &str, collect: bool) -> Result<(String, bool), String> {
+    let mut map = Map::from_file(filename)?;
+
+    let start = map.find('i');
+
+    // Plain maps have no legend overrides, so bfs and astar agree; only pay
+    // for astar's heap once a map actually declares non-uniform terrain cost.
+    let found = if collect {
+        match start {
+            Some(start_pos) => {
+                let waypoints = map.find_all('O');
+                match map.route_through(start_pos, &waypoints, false) {
+                    Some(path) if !waypoints.is_empty() => {
+                        map.mark_path(&path);
+                        true
+                    }
+                    _ => false,
+                }
+            }
+            None => false,
+        }
+    } else {
+        match (start, map.find('O')) {
+            (Some(start_pos), Some(end_pos)) => {
+                let path = if map.cost.is_empty() {
+                    map.bfs(start_pos, end_pos)
+                } else {
+                    map.astar(start_pos, end_pos)
+                };
+                match path {
+                    Some(path) => {
+                        map.mark_path(&path);
+                        true
+                    }
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    };
+
+    Ok((map.to_string(), found))
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    
-    if args.len() != 2 {
-        eprintln!("Usage: {} <map_file>", args[0]);
+
+    if args.len() < 2 {
+        eprintln!(
+            "Usage: {} <map_file_or_glob> [--depth N] [--collect]",
+            args[0]
+        );
         process::exit(1);
     }
-    
-    let filename = &args[1];
-    
-    let mut map = match Map::from_file(filename) {
-        Ok(map) => map,
-        Err(e) => {
-            eprintln!("Error: {}", e);
-            process::exit(1);
+
+    let pattern = &args[1];
+    let max_depth = args
+        .iter()
+        .position(|a| a == "--depth")
+        .and_then(|i| args.get(i + 1))
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(usize::MAX);
+    let collect = args.iter().any(|a| a == "--collect");
+
+    let files = if is_glob(pattern) {
+        match expand_glob(pattern, max_depth) {
+            Ok(files) => files,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                process::exit(1);
+            }
         }
+    } else {
+        vec![pattern.clone()]
     };
-    
-    let start = map.find('i');
-    let end = map.find('O');
 
-    if let (Some(start_pos), Some(end_pos)) = (start, end) {
-        if let Some(path) = map.bfs(start_pos, end_pos) {
-            map.mark_path(&path);
+    if files.is_empty() {
+        eprintln!("No files matched pattern '{}'", pattern);
+        process::exit(1);
+    }
+
+    let mut exit_code = 0;
+    for filename in &files {
+        if files.len() > 1 {
+            println!("== {} ==", filename);
+        }
+        match solve_map(filename, collect) {
+            Ok((rendered, found)) => {
+                println!("{}", rendered);
+                if !found {
+                    exit_code = 1;
+                }
+            }
+            Err(e) => {
+                eprintln!("Error solving '{}': {}", filename, e);
+                exit_code = 1;
+            }
         }
     }
-    
-    println!("{}", map.to_string());
+
+    process::exit(exit_code);
 }
 
 #[cfg(test)]
@@ -159,7 +748,7 @@ mod tests {
         ];
         let width = grid[0].len();
         let height = grid.len();
-        Map { grid, width, height }
+        Map::from_grid(grid, width, height)
     }
 
     #[test]
@@ -211,6 +800,88 @@ mod tests {
         std::fs::remove_file(filename).unwrap();
     }
 
+    #[test]
+    fn test_from_source_in_memory() {
+        let source = InMemoryFs::new().with_file("map.txt", "##    #\n#  #i #\n#  O## \n   #   ");
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        assert_eq!(map.height, 4);
+        assert_eq!(map.width, 7);
+        assert_eq!(map.find('i'), Some((4, 1)));
+    }
+
+    #[test]
+    fn test_structured_legend_weighted_terrain_changes_astar_route() {
+        let source = InMemoryFs::new().with_file(
+            "map.txt",
+            "~ = water 3\n---\n#######\n#i~~O #\n#.....#\n#######",
+        );
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        let start = map.find('i').unwrap();
+        let end = map.find('O').unwrap();
+
+        // The direct route crosses two costly '~' cells (3+3+1=7); going the
+        // long way around through cheap '.' cells is less costly (5*1=5),
+        // so a cost-aware astar should prefer it even though bfs, which is
+        // blind to cost, takes the shorter hop count.
+        let astar_path = map.astar(start, end).unwrap();
+        let bfs_path = map.bfs(start, end).unwrap();
+        assert_eq!(astar_path.len(), 5);
+        assert_eq!(bfs_path.len(), 3);
+    }
+
+    #[test]
+    fn test_structured_wall_legend_blocks_movement() {
+        let source = InMemoryFs::new()
+            .with_file("map.txt", "X = blocked\n---\n#####\n#iXO#\n#####");
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        let start = map.find('i').unwrap();
+        let end = map.find('O').unwrap();
+        assert!(map.bfs(start, end).is_none());
+    }
+
+    #[test]
+    fn test_structured_portal_links_linked_cells() {
+        let source = InMemoryFs::new().with_file("map.txt", "1 2\n---\ni1  \n  2O");
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        assert_eq!(map.portals.get(&(1, 0)), Some(&(2, 1)));
+        assert_eq!(map.portals.get(&(2, 1)), Some(&(1, 0)));
+
+        let start = map.find('i').unwrap();
+        let end = map.find('O').unwrap();
+        let path = map.bfs(start, end).unwrap();
+        // Stepping onto '1' warps straight to '2', next to 'O', a two-step
+        // route; on this small wrapping grid bfs returns the equally short
+        // route down to (0, 1) and across the left edge to 'O' instead.
+        assert_eq!(path.len(), 2);
+    }
+
+    #[test]
+    fn test_structured_format_falls_back_to_plain_map_without_header() {
+        let source = InMemoryFs::new().with_file("map.txt", "i O");
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        assert!(map.cost.is_empty());
+        assert!(map.portals.is_empty());
+        assert_eq!(map.find('i'), Some((0, 0)));
+    }
+
+    #[test]
+    fn test_structured_legend_parses_with_crlf_line_endings() {
+        let source = InMemoryFs::new().with_file(
+            "map.txt",
+            "~ = water 3\r\n---\r\n#####\r\n#i~O#\r\n#####",
+        );
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        assert_eq!(map.cost.get(&'~'), Some(&Some(3)));
+        assert_eq!(map.find('i'), Some((1, 1)));
+        assert_eq!(map.find('O'), Some((3, 1)));
+    }
+
+    #[test]
+    fn test_from_source_in_memory_missing_file() {
+        let source = InMemoryFs::new();
+        assert!(Map::from_source(&source, "missing.txt").is_err());
+    }
+
     #[test]
     fn test_from_file_error() {
         let result = Map::from_file("non_existent_file.txt");
@@ -219,22 +890,19 @@ mod tests {
 
     #[test]
     fn test_no_path() {
-        // Test case where there's truly no path due to complete isolation
-        // This map has 'i' and 'O' completely surrounded by walls with no gaps
+        // Test case where there's truly no path due to complete isolation.
+        // 'i' and 'O' each have all four (toroidally-wrapped) neighbors
+        // walled off, so neither can move anywhere at all.
         let grid: Vec<Vec<char>> = vec![
-            "#######".chars().collect::<Vec<char>>(),
-            "#i    #".chars().collect::<Vec<char>>(),
-            "# #### #".chars().collect::<Vec<char>>(),
-            "# #  # #".chars().collect::<Vec<char>>(),
-            "# #### #".chars().collect::<Vec<char>>(),
-            "#    O#".chars().collect::<Vec<char>>(),
-            "#######".chars().collect::<Vec<char>>(),
+            "   #   ".chars().collect::<Vec<char>>(),
+            "  #i#  ".chars().collect::<Vec<char>>(),
+            "   #   ".chars().collect::<Vec<char>>(),
+            "       ".chars().collect::<Vec<char>>(),
+            " #     ".chars().collect::<Vec<char>>(),
+            "#O#    ".chars().collect::<Vec<char>>(),
+            " #     ".chars().collect::<Vec<char>>(),
         ];
-        let map = Map {
-            grid,
-            width: 7,
-            height: 7,
-        };
+        let map = Map::from_grid(grid, 7, 7);
         
         let start = map.find('i').unwrap();
         let end = map.find('O').unwrap();
@@ -244,6 +912,228 @@ mod tests {
         assert!(path.is_none(), "Expected no path, but found one");
     }
 
+    #[test]
+    fn test_astar_matches_bfs_length_on_uniform_terrain() {
+        let map = create_test_map();
+        let bfs_path = map.bfs((4, 1), (3, 2)).unwrap();
+        let astar_path = map.astar((4, 1), (3, 2)).unwrap();
+        assert_eq!(bfs_path.len(), astar_path.len());
+    }
+
+    #[test]
+    fn test_astar_no_path() {
+        let grid: Vec<Vec<char>> = vec![
+            "   #   ".chars().collect::<Vec<char>>(),
+            "  #i#  ".chars().collect::<Vec<char>>(),
+            "   #   ".chars().collect::<Vec<char>>(),
+            "       ".chars().collect::<Vec<char>>(),
+            " #     ".chars().collect::<Vec<char>>(),
+            "#O#    ".chars().collect::<Vec<char>>(),
+            " #     ".chars().collect::<Vec<char>>(),
+        ];
+        let map = Map::from_grid(grid, 7, 7);
+
+        let start = map.find('i').unwrap();
+        let end = map.find('O').unwrap();
+        assert!(map.astar(start, end).is_none());
+    }
+
+    #[test]
+    fn test_astar_heuristic_stays_admissible_with_zero_cost_terrain() {
+        // A column of zero-cost 'z' tiles is strictly cheaper (cost 2) than
+        // the shorter-looking route through plain ' ' tiles (cost 4). A
+        // heuristic that assumes min_cost=1 instead of the declared 0
+        // overestimates and makes astar settle for the costlier route.
+        let source = InMemoryFs::new().with_file(
+            "map.txt",
+            "z = zero 0\n---\n#######\n#i z  #\n#.z   #\n#.z   #\n#.zO  #\n#######",
+        );
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        let start = map.find('i').unwrap();
+        let end = map.find('O').unwrap();
+
+        let path = map.astar(start, end).unwrap();
+        let cost: u32 = path
+            .iter()
+            .map(|&(x, y)| map.cell_cost(map.get(x, y)).unwrap())
+            .sum();
+        assert_eq!(cost, 2);
+    }
+
+    fn run_fixture(dir: &str) {
+        let entries = fs::read_dir(dir)
+            .unwrap_or_else(|e| panic!("failed to read fixture dir '{}': {}", dir, e));
+
+        let mut inputs: Vec<std::path::PathBuf> = entries
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| p.extension().and_then(|ext| ext.to_str()) == Some("in"))
+            .collect();
+        inputs.sort();
+        assert!(!inputs.is_empty(), "no '*.in' fixtures found in '{}'", dir);
+
+        for in_path in inputs {
+            let out_path = in_path.with_extension("out");
+            let input = fs::read_to_string(&in_path)
+                .unwrap_or_else(|e| panic!("failed to read '{}': {}", in_path.display(), e));
+            let expected = fs::read_to_string(&out_path)
+                .unwrap_or_else(|e| panic!("failed to read '{}': {}", out_path.display(), e));
+
+            let source = InMemoryFs::new().with_file("fixture", &input);
+            let mut map = Map::from_source(&source, "fixture")
+                .unwrap_or_else(|e| panic!("failed to parse '{}': {}", in_path.display(), e));
+
+            if let (Some(start), Some(end)) = (map.find('i'), map.find('O')) {
+                if let Some(path) = map.bfs(start, end) {
+                    map.mark_path(&path);
+                }
+            }
+
+            let actual = map.to_string();
+            if actual != expected {
+                panic!(
+                    "fixture '{}' mismatch:\n{}",
+                    in_path.display(),
+                    unified_diff(&expected, &actual)
+                );
+            }
+        }
+    }
+
+    fn unified_diff(expected: &str, actual: &str) -> String {
+        let expected_lines: Vec<&str> = expected.lines().collect();
+        let actual_lines: Vec<&str> = actual.lines().collect();
+        let mut out = String::new();
+        for i in 0..expected_lines.len().max(actual_lines.len()) {
+            let e = expected_lines.get(i).copied().unwrap_or("");
+            let a = actual_lines.get(i).copied().unwrap_or("");
+            if e == a {
+                out.push_str(&format!("  {}\n", e));
+            } else {
+                out.push_str(&format!("- {}\n", e));
+                out.push_str(&format!("+ {}\n", a));
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn test_fixtures() {
+        run_fixture("tests/data");
+    }
+
+    #[test]
+    fn test_is_glob() {
+        assert!(is_glob("maps/**/*.txt"));
+        assert!(is_glob("maps/?.txt"));
+        assert!(!is_glob("maps/a.txt"));
+    }
+
+    #[test]
+    fn test_split_glob_prefix() {
+        assert_eq!(
+            split_glob_prefix("maps/**/*.txt"),
+            ("maps".to_string(), "**/*.txt".to_string())
+        );
+        assert_eq!(
+            split_glob_prefix("../fixtures/*.txt"),
+            ("../fixtures".to_string(), "*.txt".to_string())
+        );
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.txt", "a.txt"));
+        assert!(glob_match("**/*.txt", "sub/dir/a.txt"));
+        assert!(glob_match("**/*.txt", "a.txt"));
+        assert!(!glob_match("*.txt", "a.csv"));
+    }
+
+    #[test]
+    fn test_find_all() {
+        let grid: Vec<Vec<char>> = vec![
+            "i O O".chars().collect::<Vec<char>>(),
+            "O    ".chars().collect::<Vec<char>>(),
+        ];
+        let map = Map::from_grid(grid, 5, 2);
+        let mut points = map.find_all('O');
+        points.sort();
+        assert_eq!(points, vec![(0, 1), (2, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn test_route_through_visits_every_waypoint() {
+        let grid: Vec<Vec<char>> = vec![
+            "i   O".chars().collect::<Vec<char>>(),
+            "    O".chars().collect::<Vec<char>>(),
+        ];
+        let map = Map::from_grid(grid, 5, 2);
+        let start = map.find('i').unwrap();
+        let waypoints = map.find_all('O');
+        let path = map.route_through(start, &waypoints, false).unwrap();
+        for &w in &waypoints {
+            assert!(path.contains(&w), "path should visit waypoint {:?}", w);
+        }
+    }
+
+    #[test]
+    fn test_route_through_picks_optimal_order_not_input_order() {
+        let size = 21;
+        let mut grid = vec![vec![' '; size]; size];
+        grid[0][0] = 'i';
+        grid[10][0] = 'O';
+        grid[0][10] = 'O';
+        grid[10][10] = 'O';
+        let map = Map::from_grid(grid, size, size);
+        let start = map.find('i').unwrap();
+        let waypoints = map.find_all('O');
+
+        let path = map.route_through(start, &waypoints, false).unwrap();
+
+        let mut naive_cost = 0;
+        let mut cur = start;
+        for &w in &waypoints {
+            naive_cost += map.bfs(cur, w).unwrap().len();
+            cur = w;
+        }
+
+        assert!(
+            path.len() < naive_cost,
+            "route_through should beat the naive input-order tour: {} vs {}",
+            path.len(),
+            naive_cost
+        );
+        assert_eq!(path.len(), 30);
+    }
+
+    #[test]
+    fn test_route_through_avoids_costly_terrain() {
+        let source = InMemoryFs::new().with_file(
+            "map.txt",
+            "~ = water 5\n---\n#######\n#i~~O #\n#.....#\n#######",
+        );
+        let map = Map::from_source(&source, "map.txt").unwrap();
+        let start = map.find('i').unwrap();
+        let waypoints = map.find_all('O');
+
+        // Same layout as test_structured_legend_weighted_terrain_changes_astar_route:
+        // the direct route crosses two costly '~' cells, so route_through's
+        // pairwise distances must be cost-aware (astar), not bfs hop counts,
+        // or it would walk straight through the water.
+        let path = map.route_through(start, &waypoints, false).unwrap();
+        assert_eq!(path.len(), 5);
+        for &(x, y) in &path {
+            assert_ne!(map.get(x, y), '~', "route_through should detour around water");
+        }
+    }
+
+    #[test]
+    fn test_route_through_no_waypoints() {
+        let map = create_test_map();
+        let start = map.find('i').unwrap();
+        assert_eq!(map.route_through(start, &[], false), Some(Vec::new()));
+    }
+
     #[test]
     fn test_toroidal_path() {
         // Test case that specifically uses toroidal topology
@@ -253,11 +1143,7 @@ mod tests {
             "i     O".chars().collect::<Vec<char>>(),
             "##   ##".chars().collect::<Vec<char>>(),
         ];
-        let map = Map {
-            grid,
-            width: 7,
-            height: 3,
-        };
+        let map = Map::from_grid(grid, 7, 3);
         
         let start = map.find('i').unwrap();
         let end = map.find('O').unwrap();